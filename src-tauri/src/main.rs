@@ -3,67 +3,385 @@
   windows_subsystem = "windows"
 )]
 
-#[cfg(debug_assertions)]
-use std::process::Command;
-#[cfg(not(debug_assertions))]
-use tauri::api::process::Command as TauriCommand;
-use tauri::Manager;
+// Requires in Cargo.toml: tauri with the `process-command-api` feature (for
+// the CommandEvent-based Command used below), plus `reqwest` (json-less,
+// default TLS) and `tokio` with the `time` feature for the readiness probe
+// and restart backoff.
+use tauri::api::process::{Command, CommandChild, CommandEvent};
+use tauri::{AppHandle, Manager};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-#[cfg(debug_assertions)]
-struct BackendProcess(Mutex<Option<std::process::Child>>);
+/// Fallback port used when `GYM_BACKEND_PORT` is unset or not a valid port.
+const DEFAULT_BACKEND_PORT: u16 = 4000;
+/// Fallback dev script path, resolved relative to CWD; in prod this same
+/// constant is instead resolved as a bundled resource.
+const DEFAULT_BACKEND_SCRIPT: &str = "../services/backend-server.js";
+const BACKEND_READY_TIMEOUT: Duration = Duration::from_secs(30);
+const BACKEND_READY_POLL_INTERVAL: Duration = Duration::from_millis(300);
 
-fn main() {
-  tauri::Builder::default()
-    .setup(|app| {
-            #[cfg(debug_assertions)]
-            {
-                // In Development: Run directly with Node.js using std::process::Command
-                // This gives us more control and better error reporting than Tauri's Command wrapper for this specific use case
-                println!("🚀 Starting backend server with node...");
-                
-                // Print CWD for debugging
-                if let Ok(cwd) = std::env::current_dir() {
-                    println!("📂 Current working directory: {:?}", cwd);
-                }
+/// Exponential backoff schedule for auto-restarting a crashed backend.
+const RESTART_BACKOFF_BASE: Duration = Duration::from_millis(500);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(30);
+/// A run lasting at least this long counts as healthy and resets the backoff.
+const RESTART_HEALTHY_UPTIME: Duration = Duration::from_secs(60);
+/// Auto-restart at most this many times in a row; the next crash after that
+/// gives up and emits `backend-crashed`.
+const MAX_CONSECUTIVE_RESTARTS: u32 = 6;
+
+/// Resolved backend launch settings: the port it should listen on and, in
+/// dev, the script to run. Read from `GYM_BACKEND_PORT`/`GYM_BACKEND_SCRIPT`
+/// env vars so multiple instances can run side by side during testing,
+/// falling back to the defaults above otherwise.
+struct BackendConfig {
+    port: u16,
+    script_path: std::path::PathBuf,
+}
+
+#[cfg_attr(debug_assertions, allow(unused_variables))]
+fn load_backend_config(app: &AppHandle) -> BackendConfig {
+    let port = std::env::var("GYM_BACKEND_PORT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_BACKEND_PORT);
+
+    let script_path = match std::env::var("GYM_BACKEND_SCRIPT") {
+        Ok(path) => std::path::PathBuf::from(path),
+        // In dev the script lives next to the checkout, relative to CWD, the
+        // same place the original hardcoded path looked for it — resolving
+        // it as a bundled resource would look under the build output instead.
+        #[cfg(debug_assertions)]
+        Err(_) => std::path::PathBuf::from(DEFAULT_BACKEND_SCRIPT),
+        // In prod the script is bundled as a resource alongside the app.
+        #[cfg(not(debug_assertions))]
+        Err(_) => app
+            .path_resolver()
+            .resolve_resource(DEFAULT_BACKEND_SCRIPT)
+            .unwrap_or_else(|| std::path::PathBuf::from(DEFAULT_BACKEND_SCRIPT)),
+    };
+
+    BackendConfig { port, script_path }
+}
+
+#[derive(Default)]
+struct BackendProcess {
+    child: Mutex<Option<CommandChild>>,
+    last_exit_code: Mutex<Option<i32>>,
+    last_spawn_at: Mutex<Option<Instant>>,
+    consecutive_failures: Mutex<u32>,
+    restart_count: Mutex<u32>,
+    /// Set while a shutdown or manual stop/restart is in flight so the
+    /// supervisor doesn't race an intentional kill with an auto-restart.
+    restart_suppressed: AtomicBool,
+    /// Bumped by every successful `spawn_backend`. Each forward loop captures
+    /// the generation of the child it was spawned for; a `Terminated` event
+    /// whose generation no longer matches belongs to a child that was already
+    /// superseded by a newer spawn, so the supervisor must ignore it instead
+    /// of racing a second restart against the live child.
+    generation: AtomicU64,
+}
+
+fn restart_backoff(failure_count: u32) -> Duration {
+    let exponent = failure_count.saturating_sub(1).min(6);
+    let backoff = RESTART_BACKOFF_BASE * 2u32.pow(exponent);
+    backoff.min(RESTART_BACKOFF_MAX)
+}
+
+#[derive(Clone, serde::Serialize)]
+struct BackendStatus {
+    pid: Option<u32>,
+    running: bool,
+    last_exit_code: Option<i32>,
+}
 
-                let script_path = "../services/backend-server.js";
-                
-                // Check if script exists
-                if std::path::Path::new(script_path).exists() {
-                     println!("✅ Script found at {}", script_path);
-                } else {
-                     println!("⚠️ Script NOT found at {}. Trying absolute path resolution...", script_path);
+fn forward_backend_events(
+    app: AppHandle,
+    window: tauri::Window,
+    mut rx: tauri::async_runtime::Receiver<CommandEvent>,
+    generation: u64,
+) {
+    tauri::async_runtime::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            match event {
+                CommandEvent::Stdout(line) => {
+                    let _ = window.emit("backend-log", serde_json::json!({
+                        "stream": "stdout",
+                        "line": line,
+                    }));
+                }
+                CommandEvent::Stderr(line) => {
+                    let _ = window.emit("backend-log", serde_json::json!({
+                        "stream": "stderr",
+                        "line": line,
+                    }));
                 }
-                
-                let child = Command::new("node")
-                    .arg(script_path)
-                    .stdout(std::process::Stdio::inherit())
-                    .stderr(std::process::Stdio::inherit())
-                    .stdin(std::process::Stdio::piped())
-                    .spawn();
-
-                match child {
-                    Ok(c) => {
-                        println!("✅ Backend node process spawned successfully");
-                        app.manage(BackendProcess(std::sync::Mutex::new(Some(c))));
+                CommandEvent::Terminated(payload) => {
+                    println!("❌ Backend process terminated: {:?}", payload);
+                    let state = app.state::<BackendProcess>();
+                    *state.last_exit_code.lock().unwrap() = payload.code;
+                    let _ = window.emit("backend-log", serde_json::json!({
+                        "stream": "terminated",
+                        "code": payload.code,
+                        "signal": payload.signal,
+                    }));
+
+                    if state.generation.load(Ordering::SeqCst) != generation {
+                        // This child was already replaced by a newer spawn
+                        // (manual restart/stop); the live child owns the
+                        // state now, so there is nothing left for us to do.
+                        break;
+                    }
+
+                    // The child we were tracking is confirmed dead; clear it so
+                    // `backend_status` doesn't keep reporting it as running.
+                    state.child.lock().unwrap().take();
+
+                    if state.restart_suppressed.load(Ordering::SeqCst) {
+                        break;
+                    }
+
+                    let uptime = state.last_spawn_at.lock().unwrap().map(|t| t.elapsed());
+                    let mut failures = state.consecutive_failures.lock().unwrap();
+                    if uptime.map_or(false, |u| u >= RESTART_HEALTHY_UPTIME) {
+                        *failures = 0;
                     }
-                    Err(e) => println!("❌ Failed to spawn backend node process: {}", e),
+                    *failures += 1;
+                    let failure_count = *failures;
+                    drop(failures);
+
+                    if failure_count > MAX_CONSECUTIVE_RESTARTS {
+                        println!("❌ Backend crashed {} times in a row, giving up", failure_count);
+                        // The window may still be hidden behind the readiness
+                        // gate; show it so the fatal-error screen is reachable.
+                        let _ = window.show();
+                        let _ = window.emit("backend-crashed", serde_json::json!({
+                            "consecutiveFailures": failure_count,
+                        }));
+                        break;
+                    }
+
+                    let backoff = restart_backoff(failure_count);
+                    println!("⏳ Restarting backend in {:?} (attempt {})", backoff, failure_count);
+                    let retry_app = app.clone();
+                    let retry_window = window.clone();
+                    tauri::async_runtime::spawn(async move {
+                        tokio::time::sleep(backoff).await;
+                        let state = retry_app.state::<BackendProcess>();
+                        if state.restart_suppressed.load(Ordering::SeqCst) {
+                            return;
+                        }
+                        match spawn_backend(&retry_app) {
+                            Ok(()) => {
+                                let mut restart_count = state.restart_count.lock().unwrap();
+                                *restart_count += 1;
+                                let _ = retry_window.emit("backend-restarted", serde_json::json!({
+                                    "attempt": *restart_count,
+                                }));
+                            }
+                            Err(e) => println!("❌ Failed to auto-restart backend: {}", e),
+                        }
+                    });
+                    break;
                 }
+                _ => {}
             }
+        }
+    });
+}
+
+/// Spawns the backend (node script in dev, sidecar in prod), wires up log
+/// forwarding, and stores the resulting child in the managed `BackendProcess`.
+fn spawn_backend(app: &AppHandle) -> Result<(), String> {
+    let window = app.get_window("main").expect("main window should exist");
+    let config = app.state::<BackendConfig>();
+    let port_arg = config.port.to_string();
+
+    #[cfg(debug_assertions)]
+    let spawned = {
+        println!("🚀 Starting backend server with node...");
+
+        if config.script_path.exists() {
+            println!("✅ Script found at {:?}", config.script_path);
+        } else {
+            println!("⚠️ Script NOT found at {:?}. Starting node with it anyway...", config.script_path);
+        }
 
-            #[cfg(not(debug_assertions))]
-            {
-                // In Production: Run as packaged sidecar
-                let _ = TauriCommand::new_sidecar("backend")
-                    .expect("failed to setup sidecar")
-                    .spawn()
-                    .expect("Failed to spawn sidecar");
+        Command::new("node")
+            .args([config.script_path.to_string_lossy().to_string(), "--port".into(), port_arg.clone()])
+            .envs(std::collections::HashMap::from([("PORT".to_string(), port_arg)]))
+            .spawn()
+    };
+
+    #[cfg(not(debug_assertions))]
+    let spawned = Command::new_sidecar("backend")
+        .expect("failed to setup sidecar")
+        .envs(std::collections::HashMap::from([("PORT".to_string(), port_arg)]))
+        .spawn();
+
+    match spawned {
+        Ok((rx, child)) => {
+            println!("✅ Backend process spawned successfully");
+            let state = app.state::<BackendProcess>();
+            let generation = state.generation.fetch_add(1, Ordering::SeqCst) + 1;
+            // Store the child and spawn time *before* starting the forward
+            // loop: the loop runs on another task and can observe a
+            // Terminated event almost immediately, so state must already be
+            // consistent by the time that's possible.
+            *state.child.lock().unwrap() = Some(child);
+            *state.last_spawn_at.lock().unwrap() = Some(Instant::now());
+            state.restart_suppressed.store(false, Ordering::SeqCst);
+            forward_backend_events(app.clone(), window, rx, generation);
+            Ok(())
+        }
+        Err(e) => {
+            println!("❌ Failed to spawn backend process: {}", e);
+            Err(e.to_string())
+        }
+    }
+}
+
+/// Polls the backend's health endpoint until it responds or we give up,
+/// then reveals the main window and emits `backend-ready`/`backend-failed`
+/// so the frontend never issues requests against a server that isn't up yet.
+async fn wait_for_backend_ready(window: tauri::Window, port: u16) {
+    let url = format!("http://127.0.0.1:{}/health", port);
+    let client = reqwest::Client::new();
+    let deadline = std::time::Instant::now() + BACKEND_READY_TIMEOUT;
+
+    while std::time::Instant::now() < deadline {
+        match client.get(&url).timeout(Duration::from_secs(2)).send().await {
+            Ok(resp) if resp.status().is_success() => {
+                println!("✅ Backend health check passed");
+                let _ = window.show();
+                let _ = window.emit("backend-ready", ());
+                return;
             }
-            // tauri::async_runtime::spawn(async move { ... });
+            _ => tokio::time::sleep(BACKEND_READY_POLL_INTERVAL).await,
+        }
+    }
+
+    println!("❌ Backend did not become ready within {:?}", BACKEND_READY_TIMEOUT);
+    // Reveal the window even on failure so the frontend can show a fatal-error
+    // screen instead of leaving the app as an invisible, unkillable window.
+    let _ = window.show();
+    let _ = window.emit("backend-failed", ());
+}
+
+fn backend_status_from_state(state: &BackendProcess) -> BackendStatus {
+    let guard = state.child.lock().unwrap();
+    BackendStatus {
+        pid: guard.as_ref().map(|c| c.pid()),
+        running: guard.is_some(),
+        last_exit_code: *state.last_exit_code.lock().unwrap(),
+    }
+}
+
+#[tauri::command]
+fn backend_status(state: tauri::State<BackendProcess>) -> BackendStatus {
+    backend_status_from_state(&state)
+}
+
+#[tauri::command]
+fn stop_backend(state: tauri::State<BackendProcess>) -> Result<BackendStatus, String> {
+    // Mark as intentional so the auto-restart supervisor leaves it stopped.
+    state.restart_suppressed.store(true, Ordering::SeqCst);
+    if let Some(child) = state.child.lock().unwrap().take() {
+        force_kill(child)?;
+    }
+    Ok(backend_status_from_state(&state))
+}
+
+#[tauri::command]
+fn restart_backend(app: AppHandle, state: tauri::State<BackendProcess>) -> Result<BackendStatus, String> {
+    state.restart_suppressed.store(true, Ordering::SeqCst);
+    if let Some(child) = state.child.lock().unwrap().take() {
+        let _ = force_kill(child);
+    }
+    spawn_backend(&app)?;
+    // An operator-initiated restart should start from a clean slate so a
+    // fresh crash-storm doesn't inherit failures left over from the one that
+    // prompted this restart (which may already be at the give-up threshold).
+    *state.consecutive_failures.lock().unwrap() = 0;
+    Ok(backend_status_from_state(&state))
+}
+
+/// Kills `child`, falling back to a PID-targeted OS kill if the graceful
+/// `CommandChild::kill` call itself fails. `CommandChild::kill` consumes the
+/// handle regardless of outcome, so on failure there is no handle left to
+/// retry with — without this fallback the process would be silently
+/// orphaned while state already reports it as stopped.
+fn force_kill(child: CommandChild) -> Result<(), String> {
+    let pid = child.pid();
+    if let Err(e) = child.kill() {
+        println!("⚠️ Graceful kill of backend (pid {}) failed: {}. Forcing termination.", pid, e);
+        #[cfg(target_os = "windows")]
+        let result = std::process::Command::new("taskkill")
+            .args(["/PID", &pid.to_string(), "/T", "/F"])
+            .status();
+        #[cfg(not(target_os = "windows"))]
+        let result = std::process::Command::new("kill")
+            .args(["-9", &pid.to_string()])
+            .status();
+        return result.map(|_| ()).map_err(|e| e.to_string());
+    }
+    Ok(())
+}
+
+/// Kills the backend child, terminating the whole process tree on Windows
+/// where killing just the shell wrapper leaves `node` running as an orphan.
+fn kill_backend_tree(child: CommandChild) {
+    #[cfg(target_os = "windows")]
+    {
+        let pid = child.pid();
+        let _ = std::process::Command::new("taskkill")
+            .args(["/PID", &pid.to_string(), "/T", "/F"])
+            .spawn();
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = child.kill();
+    }
+}
+
+fn main() {
+  tauri::Builder::default()
+    .manage(BackendProcess::default())
+    .invoke_handler(tauri::generate_handler![restart_backend, stop_backend, backend_status])
+    .setup(|app| {
+        let handle = app.handle();
+        let window = app.get_window("main").expect("main window should exist");
+
+        let config = load_backend_config(&handle);
+        let port = config.port;
+        app.manage(config);
+
+        // Keep the UI hidden until the backend is actually reachable, so the
+        // gym-management screens never load against a dead server.
+        let _ = window.hide();
+
+        // A failed spawn is reported through the readiness probe below
+        // rather than panicking the whole app, matching the original dev
+        // path that logged the error and kept running.
+        if let Err(e) = spawn_backend(&handle) {
+            println!("❌ Failed to spawn backend process: {}", e);
+        }
+
+        tauri::async_runtime::spawn(wait_for_backend_ready(window, port));
+
         Ok(())
     })
-    .run(tauri::generate_context!())
-    .expect("error while running tauri application");
-    println!("❌ App loop exited unexpectedly!");
+    .build(tauri::generate_context!())
+    .expect("error while building tauri application")
+    .run(|app_handle, event| match event {
+        tauri::RunEvent::ExitRequested { .. } | tauri::RunEvent::Exit => {
+            let state = app_handle.state::<BackendProcess>();
+            state.restart_suppressed.store(true, Ordering::SeqCst);
+            if let Some(child) = state.child.lock().unwrap().take() {
+                println!("🛑 Killing backend process on application exit");
+                kill_backend_tree(child);
+            }
+        }
+        _ => {}
+    });
 }